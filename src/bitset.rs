@@ -0,0 +1,78 @@
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A fixed-size bitset backed by a word array. Used to track descendant
+/// sample sets per node without allocating a `Vec<NodeId>` for each one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Bitset {
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    pub fn new(num_bits: usize) -> Self {
+        let num_words = num_bits.div_ceil(WORD_BITS);
+        Bitset {
+            words: vec![0; num_words],
+        }
+    }
+
+    pub fn set(&mut self, bit: usize) {
+        self.words[bit / WORD_BITS] |= 1u64 << (bit % WORD_BITS);
+    }
+
+    pub fn test(&self, bit: usize) -> bool {
+        self.words[bit / WORD_BITS] & (1u64 << (bit % WORD_BITS)) != 0
+    }
+
+    /// Sets every bit that is set in `other`, leaving other bits untouched.
+    pub fn union(&mut self, other: &Bitset) {
+        for (w, o) in self.words.iter_mut().zip(other.words.iter()) {
+            *w |= o;
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Iterates over the indices of every set bit, in ascending order.
+    pub fn iter_set_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_idx, &word)| {
+            (0..WORD_BITS)
+                .filter(move |bit| word & (1u64 << bit) != 0)
+                .map(move |bit| word_idx * WORD_BITS + bit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Bitset;
+
+    #[test]
+    fn test_set_and_test() {
+        let mut b = Bitset::new(70);
+        assert!(!b.test(0));
+        b.set(0);
+        b.set(63);
+        b.set(64);
+        b.set(69);
+        assert!(b.test(0));
+        assert!(b.test(63));
+        assert!(b.test(64));
+        assert!(b.test(69));
+        assert!(!b.test(1));
+    }
+
+    #[test]
+    fn test_union_and_count_ones() {
+        let mut a = Bitset::new(10);
+        a.set(1);
+        a.set(5);
+        let mut c = Bitset::new(10);
+        c.set(5);
+        c.set(8);
+        a.union(&c);
+        assert_eq!(a.count_ones(), 3);
+        assert_eq!(a.iter_set_bits().collect::<Vec<_>>(), vec![1, 5, 8]);
+    }
+}
@@ -0,0 +1 @@
+pub type NodeId = usize;
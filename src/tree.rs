@@ -1,20 +1,174 @@
+use crate::bitset::Bitset;
 use crate::node::NodeId;
 use std::cmp;
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct Tree {
     pub parent: Vec<Option<NodeId>>,
+    left_child: Vec<Option<NodeId>>,
+    right_sib: Vec<Option<NodeId>>,
+    left_sib: Vec<Option<NodeId>>,
+    is_sample: Vec<bool>,
+    samples_below: Vec<Bitset>,
 }
 
 impl Tree {
     pub fn new(parent: Vec<Option<NodeId>>) -> Self {
-        Tree { parent }
+        let n = parent.len();
+        Tree {
+            parent,
+            left_child: vec![None; n],
+            right_sib: vec![None; n],
+            left_sib: vec![None; n],
+            is_sample: vec![false; n],
+            samples_below: vec![Bitset::new(n); n],
+        }
     }
 
     pub fn parent(&self, u: NodeId) -> Option<NodeId> {
         self.parent.get(u)?.clone()
     }
 
+    pub(crate) fn set_parent(&mut self, u: NodeId, parent: Option<NodeId>) {
+        self.parent[u] = parent;
+    }
+
+    /// Splices `child` onto the head of `parent`'s child list in `O(1)`.
+    pub(crate) fn insert_child(&mut self, parent: NodeId, child: NodeId) {
+        let old_head = self.left_child[parent];
+        self.right_sib[child] = old_head;
+        self.left_sib[child] = None;
+        if let Some(head) = old_head {
+            self.left_sib[head] = Some(child);
+        }
+        self.left_child[parent] = Some(child);
+    }
+
+    /// Removes `child` from `parent`'s child list in `O(1)`.
+    pub(crate) fn remove_child(&mut self, parent: NodeId, child: NodeId) {
+        let left = self.left_sib[child];
+        let right = self.right_sib[child];
+        match left {
+            Some(l) => self.right_sib[l] = right,
+            None => self.left_child[parent] = right,
+        }
+        if let Some(r) = right {
+            self.left_sib[r] = left;
+        }
+        self.left_sib[child] = None;
+        self.right_sib[child] = None;
+    }
+
+    /// Returns an iterator over the direct children of `u`, in no
+    /// particular order.
+    pub fn children(&self, u: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        ChildrenIter {
+            tree: self,
+            next: self.left_child[u],
+        }
+    }
+
+    /// Returns an iterator over the parentless nodes of this `Tree`.
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes().filter(move |&u| self.parent(u).is_none())
+    }
+
+    /// Returns a pre-order (parents before children) traversal of every
+    /// node reachable from a root.
+    pub fn preorder(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut order = vec![];
+        let mut stack: Vec<NodeId> = self.roots().collect();
+        stack.reverse();
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            let mut children: Vec<NodeId> = self.children(node).collect();
+            children.reverse();
+            stack.extend(children);
+        }
+        order.into_iter()
+    }
+
+    /// Returns a post-order (children before parents) traversal of every
+    /// node reachable from a root.
+    pub fn postorder(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut to_visit: Vec<NodeId> = self.roots().collect();
+        let mut visited = vec![];
+        while let Some(node) = to_visit.pop() {
+            visited.push(node);
+            to_visit.extend(self.children(node));
+        }
+        visited.reverse();
+        visited.into_iter()
+    }
+
+    /// Marks `u` as a sample, seeding its descendant-sample bitset with
+    /// itself. Called once up front, before any edges are added, so that
+    /// `TreeSequenceStreamingIterator::advance` can propagate sample
+    /// membership up the parent chain as edges come and go.
+    pub(crate) fn mark_sample(&mut self, u: NodeId) {
+        self.is_sample[u] = true;
+        self.samples_below[u].set(u);
+    }
+
+    /// Unions `child`'s descendant-sample set into `start` and every
+    /// ancestor above it. Called when an edge `(child, start)` is inserted.
+    pub(crate) fn add_descendant_samples(&mut self, start: NodeId, child: NodeId) {
+        let child_samples = self.samples_below[child].clone();
+        let mut cur = Some(start);
+        while let Some(node) = cur {
+            self.samples_below[node].union(&child_samples);
+            cur = self.parent(node);
+        }
+    }
+
+    /// Recomputes `node`'s descendant-sample set from scratch as the union
+    /// of its own sample bit (if any) and `children`'s sets. Returns
+    /// whether the recomputed set differs from the previous one, so
+    /// callers can stop propagating once an ancestor chain stabilizes.
+    pub(crate) fn recompute_samples_below(&mut self, node: NodeId, children: &[NodeId]) -> bool {
+        let mut recomputed = Bitset::new(self.parent.len());
+        if self.is_sample[node] {
+            recomputed.set(node);
+        }
+        for &child in children {
+            recomputed.union(&self.samples_below[child]);
+        }
+        let changed = recomputed != self.samples_below[node];
+        self.samples_below[node] = recomputed;
+        changed
+    }
+
+    /// Recomputes the descendant-sample bitset of `start` and propagates
+    /// upward, stopping as soon as an ancestor's bitset is unchanged. Used
+    /// after a child is detached, since unioning cannot be undone in place.
+    pub(crate) fn recompute_samples_upward(&mut self, start: NodeId) {
+        let mut cur = Some(start);
+        while let Some(node) = cur {
+            let children: Vec<NodeId> = self.children(node).collect();
+            if !self.recompute_samples_below(node, &children) {
+                break;
+            }
+            cur = self.parent(node);
+        }
+    }
+
+    /// Returns the number of samples below `u` in the tree (including `u`
+    /// itself, if it is a sample).
+    pub fn num_samples(&self, u: NodeId) -> usize {
+        self.samples_below[u].count_ones()
+    }
+
+    /// Iterates over the samples below `u` in the tree (including `u`
+    /// itself, if it is a sample).
+    pub fn samples_below(&self, u: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.samples_below[u].iter_set_bits()
+    }
+
+    /// Returns an iterator over every node id tracked by this `Tree`.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        0..self.parent.len()
+    }
+
     /// Returns the most recent common ancestor of two nodes in the `Tree`.
     /// `None` is returned if the nodes do not share a common ancestor
     /// (they are under different roots).
@@ -62,11 +216,113 @@ impl Tree {
         }
         chain
     }
+
+    /// Preprocesses this `Tree` for `O(log n)` ancestor queries via binary
+    /// lifting. Build once and reuse across many `LcaIndex::lca` calls,
+    /// rather than paying the `O(depth)` cost of `mrca` on every query.
+    pub fn build_lca_index(&self) -> LcaIndex {
+        let n = self.parent.len();
+        let mut depth = vec![0u32; n];
+        let mut known = vec![false; n];
+
+        for start in 0..n {
+            if known[start] {
+                continue;
+            }
+            let mut chain = vec![start];
+            let mut cur = start;
+            while let Some(p) = self.parent(cur) {
+                if known[p] {
+                    break;
+                }
+                chain.push(p);
+                cur = p;
+            }
+            let base_depth = match self.parent(cur) {
+                Some(p) if known[p] => depth[p] + 1,
+                _ => 0,
+            };
+            for (i, &node) in chain.iter().rev().enumerate() {
+                depth[node] = base_depth + i as u32;
+                known[node] = true;
+            }
+        }
+
+        let mut up = vec![self.parent.clone()];
+        let mut k = 0;
+        while (1usize << k) < cmp::max(n, 1) {
+            let prev = &up[k];
+            let next = (0..n).map(|v| prev[v].and_then(|mid| prev[mid])).collect();
+            up.push(next);
+            k += 1;
+        }
+
+        LcaIndex { depth, up }
+    }
+}
+
+struct ChildrenIter<'a> {
+    tree: &'a Tree,
+    next: Option<NodeId>,
+}
+
+impl Iterator for ChildrenIter<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let child = self.next?;
+        self.next = self.tree.right_sib[child];
+        Some(child)
+    }
+}
+
+/// A binary-lifting index over a `Tree`'s ancestor relation, built by
+/// `Tree::build_lca_index`. Answers `lca` queries in `O(log n)` instead of
+/// the `O(depth)` two-chain walk that `Tree::mrca` performs.
+#[derive(PartialEq, Eq, Debug)]
+pub struct LcaIndex {
+    depth: Vec<u32>,
+    up: Vec<Vec<Option<NodeId>>>,
+}
+
+impl LcaIndex {
+    /// Returns the most recent common ancestor of `u` and `v`, or `None` if
+    /// they sit under different roots. Mirrors `Tree::mrca`'s semantics.
+    pub fn lca(&self, u: NodeId, v: NodeId) -> Option<NodeId> {
+        let mut u = u;
+        let mut v = v;
+        if self.depth[u] < self.depth[v] {
+            std::mem::swap(&mut u, &mut v);
+        }
+
+        let diff = self.depth[u] - self.depth[v];
+        for (k, level) in self.up.iter().enumerate() {
+            if diff & (1 << k) != 0 {
+                u = level[u]?;
+            }
+        }
+
+        if u == v {
+            return Some(u);
+        }
+
+        for level in self.up.iter().rev() {
+            if level[u] != level[v] {
+                u = level[u]?;
+                v = level[v]?;
+            }
+        }
+
+        match (self.up[0][u], self.up[0][v]) {
+            (Some(pu), Some(pv)) if pu == pv => Some(pu),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Tree;
+    use super::{NodeId, Tree};
 
     #[test]
     fn test_parent() {
@@ -97,4 +353,71 @@ mod test {
         assert_eq!(t2.mrca(3, 1), Some(1));
         assert_eq!(t2.mrca(3, 2), Some(2));
     }
+
+    #[test]
+    fn test_lca_index_matches_mrca() {
+        let t = Tree::new(vec![None, Some(0), Some(0)]);
+        let idx = t.build_lca_index();
+        assert_eq!(idx.lca(0, 1), t.mrca(0, 1));
+        assert_eq!(idx.lca(0, 2), t.mrca(0, 2));
+        assert_eq!(idx.lca(1, 2), t.mrca(1, 2));
+
+        let t2 = Tree::new(vec![None, Some(0), Some(1), Some(2)]);
+        let idx2 = t2.build_lca_index();
+        assert_eq!(idx2.lca(0, 1), t2.mrca(0, 1));
+        assert_eq!(idx2.lca(0, 2), t2.mrca(0, 2));
+        assert_eq!(idx2.lca(0, 3), t2.mrca(0, 3));
+        assert_eq!(idx2.lca(1, 2), t2.mrca(1, 2));
+        assert_eq!(idx2.lca(1, 3), t2.mrca(1, 3));
+        assert_eq!(idx2.lca(2, 3), t2.mrca(2, 3));
+        assert_eq!(idx2.lca(3, 1), t2.mrca(3, 1));
+    }
+
+    #[test]
+    fn test_lca_index_different_roots() {
+        let t = Tree::new(vec![None, None]);
+        let idx = t.build_lca_index();
+        assert_eq!(idx.lca(0, 1), None);
+    }
+
+    fn tree_with_children() -> Tree {
+        // 2 roots (0 and 4): 0 -> {1, 2}, 2 -> {3}, 4 -> {} (leaf root)
+        let mut t = Tree::new(vec![None, Some(0), Some(0), Some(2), None]);
+        t.insert_child(0, 2);
+        t.insert_child(0, 1);
+        t.insert_child(2, 3);
+        t
+    }
+
+    #[test]
+    fn test_children_and_roots() {
+        let t = tree_with_children();
+        let mut roots: Vec<_> = t.roots().collect();
+        roots.sort();
+        assert_eq!(roots, vec![0, 4]);
+
+        let mut children_of_0: Vec<_> = t.children(0).collect();
+        children_of_0.sort();
+        assert_eq!(children_of_0, vec![1, 2]);
+
+        assert_eq!(t.children(3).collect::<Vec<_>>(), vec![]);
+        assert_eq!(t.children(4).collect::<Vec<_>>(), vec![]);
+    }
+
+    #[test]
+    fn test_preorder_and_postorder() {
+        let t = tree_with_children();
+
+        let preorder: Vec<_> = t.preorder().collect();
+        assert_eq!(preorder.len(), 5);
+        let pos = |n: NodeId| preorder.iter().position(|&x| x == n).unwrap();
+        assert!(pos(0) < pos(1) && pos(0) < pos(2));
+        assert!(pos(2) < pos(3));
+
+        let postorder: Vec<_> = t.postorder().collect();
+        assert_eq!(postorder.len(), 5);
+        let pos = |n: NodeId| postorder.iter().position(|&x| x == n).unwrap();
+        assert!(pos(1) < pos(0) && pos(2) < pos(0));
+        assert!(pos(3) < pos(2));
+    }
 }
@@ -2,32 +2,133 @@ use crate::edge::Edge;
 use crate::node::NodeId;
 use crate::tree::Tree;
 use std::cmp;
+use std::fmt;
 use std::vec::Vec;
 
 use streaming_iterator::StreamingIterator;
 
-#[derive(PartialEq, Eq, Debug)]
+/// Errors returned by the `try_*` validating edge-table builders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeSequenceError {
+    /// An edge with the same `child`, `parent` and `left` is already present
+    /// (whatever its `right`).
+    DuplicateEdge,
+    /// `child` already has a different parent over an overlapping `[left, right)` interval.
+    OverlappingParentInterval,
+    /// `left >= right`, so the interval covers no genomic positions.
+    InvalidInterval,
+    /// There is no current edge for the child being transplanted.
+    UnknownChild,
+    /// Adding the edge would make `child` its own ancestor somewhere in `[left, right)`.
+    WouldCreateCycle,
+}
+
+impl fmt::Display for TreeSequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            TreeSequenceError::DuplicateEdge => "cannot have duplicate edges",
+            TreeSequenceError::OverlappingParentInterval => {
+                "child already has a different parent over an overlapping interval"
+            }
+            TreeSequenceError::InvalidInterval => "edge interval must have left < right",
+            TreeSequenceError::UnknownChild => "no current edge exists for this child",
+            TreeSequenceError::WouldCreateCycle => "edge would make a child its own ancestor",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+impl std::error::Error for TreeSequenceError {}
+
+#[derive(Debug)]
 pub struct TreeSequence {
     num_nodes: usize,
     edges: Vec<Edge>,
+    // Indices into `edges`, kept sorted by left endpoint: the order in
+    // which edges are inserted as the genome sweeps from left to right.
+    order_by_left: Vec<usize>,
+    // Indices into `edges`, kept sorted by right endpoint: the order in
+    // which edges are removed as the genome sweeps from left to right.
+    order_by_right: Vec<usize>,
+    samples: Vec<bool>,
 }
 
+// `edges` is append-only and reflects insertion order rather than sorted
+// order, so two `TreeSequence`s built from the same edges in a different
+// order would otherwise compare unequal. Compare the left-sorted
+// projection instead, which is canonical regardless of insertion order.
+impl PartialEq for TreeSequence {
+    fn eq(&self, other: &Self) -> bool {
+        self.num_nodes == other.num_nodes
+            && self.samples == other.samples
+            && self.order_by_left.len() == other.order_by_left.len()
+            && self
+                .order_by_left
+                .iter()
+                .zip(other.order_by_left.iter())
+                .all(|(&i, &j)| self.edges[i] == other.edges[j])
+    }
+}
+
+impl Eq for TreeSequence {}
+
 impl TreeSequence {
     pub fn new() -> Self {
         TreeSequence {
             num_nodes: 0,
             edges: vec![],
+            order_by_left: vec![],
+            order_by_right: vec![],
+            samples: vec![],
         }
     }
 
     pub fn iter(&self) -> TreeSequenceIterator {
-        let edges: Vec<&Edge> = self.edges.iter().rev().collect();
-        TreeSequenceIterator::new(self.num_nodes, edges)
+        TreeSequenceIterator::new(self.num_nodes, &self.edges, &self.order_by_left, &self.order_by_right)
     }
 
     pub fn streaming_iter(&self) -> TreeSequenceStreamingIterator {
-        let edges: Vec<&Edge> = self.edges.iter().rev().collect();
-        TreeSequenceStreamingIterator::new(self.num_nodes, edges)
+        TreeSequenceStreamingIterator::new(
+            self.new_tree(),
+            &self.edges,
+            &self.order_by_left,
+            &self.order_by_right,
+        )
+    }
+
+    /// Builds the `Tree` covering the genomic coordinate `position`, by
+    /// binary-searching `order_by_left` for the edges whose interval could
+    /// contain `position` and inserting the ones that actually do, rather
+    /// than iterating over every tree from the start of the sequence.
+    pub fn tree_at(&self, position: u64) -> Tree {
+        let mut tree = self.new_tree();
+
+        let num_candidates = self.order_by_left.partition_point(|&i| self.edges[i].left <= position);
+        for &idx in &self.order_by_left[..num_candidates] {
+            let e = &self.edges[idx];
+            if e.right <= position {
+                continue;
+            }
+            tree.set_parent(e.child, Some(e.parent));
+            tree.insert_child(e.parent, e.child);
+            tree.add_descendant_samples(e.parent, e.child);
+        }
+
+        tree
+    }
+
+    /// Marks the given nodes as samples. Descendant-sample bitsets
+    /// maintained while iterating are seeded from this set, so call it
+    /// before iterating.
+    pub fn mark_samples(&mut self, samples: &[NodeId]) {
+        let max_id = samples.iter().cloned().max().map_or(0, |m| m + 1);
+        self.num_nodes = cmp::max(self.num_nodes, max_id);
+        if self.samples.len() < self.num_nodes {
+            self.samples.resize(self.num_nodes, false);
+        }
+        for &s in samples {
+            self.samples[s] = true;
+        }
     }
 
     pub fn for_each_with_index<F>(&self, f: F)
@@ -42,33 +143,122 @@ impl TreeSequence {
         }
     }
 
+    /// Panicking wrapper around [`TreeSequence::try_add_edge`], for callers
+    /// that trust their input to already be well-formed.
     pub fn add_edge(&mut self, child: NodeId, parent: NodeId, left: u64, right: u64) {
+        self.try_add_edge(child, parent, left, right).unwrap();
+    }
+
+    /// Validates and inserts an edge, rejecting malformed or conflicting
+    /// input instead of panicking. Rejects: intervals with `left >= right`,
+    /// edges with the same `child`, `parent` and `left` as an existing one,
+    /// a child assigned a different parent over an
+    /// overlapping interval, and edges that would make `child` its own
+    /// ancestor within `[left, right)`.
+    pub fn try_add_edge(
+        &mut self,
+        child: NodeId,
+        parent: NodeId,
+        left: u64,
+        right: u64,
+    ) -> Result<(), TreeSequenceError> {
+        if left >= right {
+            return Err(TreeSequenceError::InvalidInterval);
+        }
+        if self
+            .edges
+            .iter()
+            .any(|e| e.child == child && e.parent != parent && overlaps(e.left, e.right, left, right))
+        {
+            return Err(TreeSequenceError::OverlappingParentInterval);
+        }
+        if self.would_create_cycle(child, parent, left, right) {
+            return Err(TreeSequenceError::WouldCreateCycle);
+        }
+
         let e = Edge {
             child,
             parent,
             left,
             right,
         };
+
+        let left_pos = self.order_by_left.binary_search_by(|&i| self.edges[i].cmp(&e));
+        let Err(left_pos) = left_pos else {
+            return Err(TreeSequenceError::DuplicateEdge);
+        };
+
         self.num_nodes = cmp::max(self.num_nodes, cmp::max(child, parent) + 1);
-        match self.edges.binary_search(&e) {
-            Ok(_) => panic!("Cannot have duplicate edges"),
-            Err(pos) => self.edges.insert(pos, e),
+        let idx = self.edges.len();
+        let right_pos = self
+            .order_by_right
+            .binary_search_by(|&i| (self.edges[i].right, self.edges[i].parent, self.edges[i].child).cmp(&(right, parent, child)))
+            .unwrap_or_else(|pos| pos);
+
+        self.edges.push(e);
+        self.order_by_left.insert(left_pos, idx);
+        self.order_by_right.insert(right_pos, idx);
+        Ok(())
+    }
+
+    // Walks upward from `parent` through every edge active somewhere in
+    // `[left, right)`, narrowing the interval to the overlap at each step,
+    // and returns true if any walk reaches `child` (which would make `child`
+    // its own ancestor once the new edge is added).
+    fn would_create_cycle(&self, child: NodeId, parent: NodeId, left: u64, right: u64) -> bool {
+        if parent == child {
+            return true;
         }
+        self.edges.iter().any(|e| {
+            e.child == parent
+                && overlaps(e.left, e.right, left, right)
+                && self.would_create_cycle(child, e.parent, cmp::max(left, e.left), cmp::min(right, e.right))
+        })
+    }
+
+    // Builds a fresh, edgeless `Tree` with the current sample set seeded in,
+    // ready to have edge diffs replayed into it.
+    fn new_tree(&self) -> Tree {
+        let mut tree = Tree::new(vec![None; self.num_nodes]);
+        for (node, &is_sample) in self.samples.iter().enumerate() {
+            if is_sample {
+                tree.mark_sample(node);
+            }
+        }
+        tree
     }
 }
 
+// Whether the half-open intervals `[a_left, a_right)` and `[b_left, b_right)` intersect.
+fn overlaps(a_left: u64, a_right: u64, b_left: u64, b_right: u64) -> bool {
+    a_left < b_right && b_left < a_right
+}
+
 pub struct TreeSequenceIterator<'a> {
-    num_nodes: usize,
-    current_edges: Vec<&'a Edge>,
-    upcoming_edges: Vec<&'a Edge>,
+    edges: &'a [Edge],
+    order_by_left: &'a [usize],
+    order_by_right: &'a [usize],
+    left_cursor: usize,
+    right_cursor: usize,
+    position: u64,
+    parent: Vec<Option<NodeId>>,
 }
 
 impl<'a> TreeSequenceIterator<'a> {
-    fn new(num_nodes: usize, edges: Vec<&'a Edge>) -> Self {
+    fn new(
+        num_nodes: usize,
+        edges: &'a [Edge],
+        order_by_left: &'a [usize],
+        order_by_right: &'a [usize],
+    ) -> Self {
         TreeSequenceIterator {
-            num_nodes,
-            current_edges: vec![],
-            upcoming_edges: edges,
+            edges,
+            order_by_left,
+            order_by_right,
+            left_cursor: 0,
+            right_cursor: 0,
+            position: 0,
+            parent: vec![None; num_nodes],
         }
     }
 }
@@ -76,61 +266,65 @@ impl<'a> TreeSequenceIterator<'a> {
 impl Iterator for TreeSequenceIterator<'_> {
     type Item = Tree;
 
-    // TODO way to index edges by right index so we can quickly remove them.
-    // outgoing edges. upcoming_edges are already sorted by left endpoint so we
-    // don't have this problem for figuring out how many new edges to pull in.
     fn next(&mut self) -> Option<Self::Item> {
-        // Remove outgoing edges
-        if let Some(&out_edge) = self.current_edges.iter().min_by_key(|&&e| e.right) {
-            self.current_edges.retain(|&e| e.right > out_edge.right);
-        }
-
-        // Add incoming edges
-        if let Some(&e) = self.upcoming_edges.last() {
-            let new_right = e.left;
-            while let Some(&e) = self.upcoming_edges.last() {
-                if e.left > new_right {
-                    break;
-                }
-                self.current_edges.push(self.upcoming_edges.pop().unwrap());
-            }
+        while self.right_cursor < self.order_by_right.len()
+            && self.edges[self.order_by_right[self.right_cursor]].right == self.position
+        {
+            let child = self.edges[self.order_by_right[self.right_cursor]].child;
+            self.parent[child] = None;
+            self.right_cursor += 1;
         }
 
-        if self.current_edges.is_empty() && self.upcoming_edges.is_empty() {
+        while self.left_cursor < self.order_by_left.len()
+            && self.edges[self.order_by_left[self.left_cursor]].left == self.position
+        {
+            let idx = self.order_by_left[self.left_cursor];
+            self.parent[self.edges[idx].child] = Some(self.edges[idx].parent);
+            self.left_cursor += 1;
+        }
+
+        let next_left = self.order_by_left.get(self.left_cursor).map(|&i| self.edges[i].left);
+        let next_right = self.order_by_right.get(self.right_cursor).map(|&i| self.edges[i].right);
+        self.position = match (next_left, next_right) {
+            (Some(l), Some(r)) => cmp::min(l, r),
+            (Some(l), None) => l,
+            (None, Some(r)) => r,
+            (None, None) => self.position,
+        };
+
+        if self.left_cursor >= self.order_by_left.len() && self.right_cursor >= self.order_by_right.len() {
             None
         } else {
-            // This is interesting. Ideally I would want to mantain a mut Tree
-            // in the iterator and return a reference to it. It's significantly
-            // faster to update the children of outgoing and incoming edges each
-            // time than to construct the whole tree again...
-            // It doesn't look this is a very easy thing to do without
-            // generic associative types...
-            // See StreamingIterator below for the "solution" to this
-            let mut parent: Vec<Option<NodeId>> = vec![];
-            parent.resize_with(self.num_nodes, Default::default);
-            for &e in &self.current_edges {
-                parent[e.child] = Some(e.parent);
-            }
-
-            Some(Tree::new(parent))
+            Some(Tree::new(self.parent.clone()))
         }
     }
 }
 
 pub struct TreeSequenceStreamingIterator<'a> {
     tree: Tree,
-    current_edges: Vec<&'a Edge>,
-    upcoming_edges: Vec<&'a Edge>,
+    edges: &'a [Edge],
+    order_by_left: &'a [usize],
+    order_by_right: &'a [usize],
+    left_cursor: usize,
+    right_cursor: usize,
+    position: u64,
 }
 
 impl<'a> TreeSequenceStreamingIterator<'a> {
-    fn new(num_nodes: usize, edges: Vec<&'a Edge>) -> Self {
-        let mut parent: Vec<Option<NodeId>> = vec![];
-        parent.resize_with(num_nodes, Default::default);
+    fn new(
+        tree: Tree,
+        edges: &'a [Edge],
+        order_by_left: &'a [usize],
+        order_by_right: &'a [usize],
+    ) -> Self {
         TreeSequenceStreamingIterator {
-            tree: Tree::new(parent),
-            current_edges: vec![],
-            upcoming_edges: edges,
+            tree,
+            edges,
+            order_by_left,
+            order_by_right,
+            left_cursor: 0,
+            right_cursor: 0,
+            position: 0,
         }
     }
 }
@@ -139,35 +333,102 @@ impl StreamingIterator for TreeSequenceStreamingIterator<'_> {
     type Item = Tree;
 
     fn advance(&mut self) {
-        // Remove outgoing edges
-        if let Some(&out_edge) = self.current_edges.iter().min_by_key(|&&e| e.right) {
-            for e in self.current_edges.iter() {
-                if e.right == out_edge.right {
-                    self.tree.set_parent(e.child, None);
-                }
-            }
-            self.current_edges.retain(|&e| e.right > out_edge.right);
-        }
-
-        // Add incoming edges
-        if let Some(&e) = self.upcoming_edges.last() {
-            let new_right = e.left;
-            while let Some(&e) = self.upcoming_edges.last() {
-                if e.left > new_right {
-                    break;
-                }
-                let new_edge = self.upcoming_edges.pop().unwrap();
-                self.current_edges.push(new_edge);
-                self.tree.set_parent(e.child, Some(e.parent));
-            }
+        if self.left_cursor >= self.order_by_left.len() && self.right_cursor >= self.order_by_right.len() {
+            return;
         }
+
+        let mut orphaned_parents = vec![];
+        while self.right_cursor < self.order_by_right.len()
+            && self.edges[self.order_by_right[self.right_cursor]].right == self.position
+        {
+            let e = &self.edges[self.order_by_right[self.right_cursor]];
+            self.tree.set_parent(e.child, None);
+            self.tree.remove_child(e.parent, e.child);
+            orphaned_parents.push(e.parent);
+            self.right_cursor += 1;
+        }
+        for parent in orphaned_parents {
+            self.tree.recompute_samples_upward(parent);
+        }
+
+        while self.left_cursor < self.order_by_left.len()
+            && self.edges[self.order_by_left[self.left_cursor]].left == self.position
+        {
+            let e = &self.edges[self.order_by_left[self.left_cursor]];
+            self.tree.set_parent(e.child, Some(e.parent));
+            self.tree.insert_child(e.parent, e.child);
+            self.tree.add_descendant_samples(e.parent, e.child);
+            self.left_cursor += 1;
+        }
+
+        let next_left = self.order_by_left.get(self.left_cursor).map(|&i| self.edges[i].left);
+        let next_right = self.order_by_right.get(self.right_cursor).map(|&i| self.edges[i].right);
+        self.position = match (next_left, next_right) {
+            (Some(l), Some(r)) => cmp::min(l, r),
+            (Some(l), None) => l,
+            (None, Some(r)) => r,
+            (None, None) => self.position,
+        };
     }
 
     fn get(&self) -> Option<&Self::Item> {
-        if self.current_edges.is_empty() && self.upcoming_edges.is_empty() {
+        if self.left_cursor >= self.order_by_left.len() && self.right_cursor >= self.order_by_right.len() {
             None
         } else {
             Some(&self.tree)
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{TreeSequence, TreeSequenceError};
+
+    #[test]
+    fn test_try_add_edge_invalid_interval() {
+        let mut ts = TreeSequence::new();
+        assert_eq!(
+            ts.try_add_edge(0, 1, 5, 5),
+            Err(TreeSequenceError::InvalidInterval)
+        );
+        assert_eq!(
+            ts.try_add_edge(0, 1, 5, 3),
+            Err(TreeSequenceError::InvalidInterval)
+        );
+    }
+
+    #[test]
+    fn test_try_add_edge_duplicate() {
+        let mut ts = TreeSequence::new();
+        ts.try_add_edge(0, 1, 0, 10).unwrap();
+        assert_eq!(
+            ts.try_add_edge(0, 1, 0, 10),
+            Err(TreeSequenceError::DuplicateEdge)
+        );
+    }
+
+    #[test]
+    fn test_try_add_edge_overlapping_parent_interval() {
+        let mut ts = TreeSequence::new();
+        ts.try_add_edge(0, 1, 0, 10).unwrap();
+        assert_eq!(
+            ts.try_add_edge(0, 2, 5, 15),
+            Err(TreeSequenceError::OverlappingParentInterval)
+        );
+        // Non-overlapping intervals for the same child are fine.
+        assert!(ts.try_add_edge(0, 2, 10, 15).is_ok());
+    }
+
+    #[test]
+    fn test_try_add_edge_would_create_cycle() {
+        let mut ts = TreeSequence::new();
+        ts.try_add_edge(0, 1, 0, 10).unwrap();
+        ts.try_add_edge(1, 2, 0, 10).unwrap();
+        assert_eq!(
+            ts.try_add_edge(2, 0, 0, 10),
+            Err(TreeSequenceError::WouldCreateCycle)
+        );
+        // A non-overlapping interval doesn't conflict with the existing chain.
+        assert!(ts.try_add_edge(2, 0, 10, 20).is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::treeseq::TreeSequence;
+use crate::treeseq::{TreeSequence, TreeSequenceError};
 use crate::node::NodeId;
 
 pub struct TreeSequenceBuilder {
@@ -28,29 +28,55 @@ impl TreeSequenceBuilder {
         self
     }
 
-    pub fn transplant(mut self, children: Vec<NodeId>, new_parent: Option<NodeId>) -> Self {
+    /// Panicking wrapper around [`TreeSequenceBuilder::try_transplant`], for
+    /// callers that trust their input to already be well-formed.
+    pub fn transplant(self, children: Vec<NodeId>, new_parent: Option<NodeId>) -> Self {
+        self.try_transplant(children, new_parent).unwrap()
+    }
+
+    /// Moves each of `children` from its current parent onto `new_parent`
+    /// (or makes it a root if `new_parent` is `None`), closing out its
+    /// existing edge at the current breakpoint. Fails with
+    /// `UnknownChild` if a child has no current edge to move.
+    pub fn try_transplant(
+        mut self,
+        children: Vec<NodeId>,
+        new_parent: Option<NodeId>,
+    ) -> Result<Self, TreeSequenceError> {
         for c in children {
-            // Flush the existing edge for that child if there is one
-            if let Some(index) = self.curr_edges.iter().position(|(child, _, _)| *child == c) {
-                let (child, old_parent, left) = self.curr_edges.remove(index);
-                self.ts
-                    .add_edge(child, old_parent, left, self.last_breakpoint);
-            }
+            // Flush the existing edge for that child.
+            let index = self
+                .curr_edges
+                .iter()
+                .position(|(child, _, _)| *child == c)
+                .ok_or(TreeSequenceError::UnknownChild)?;
+            let (child, old_parent, left) = self.curr_edges.remove(index);
+            self.ts
+                .try_add_edge(child, old_parent, left, self.last_breakpoint)?;
+
             // Start a new edge for the child if it has a new parent
             if let Some(new_parent) = new_parent {
                 self.curr_edges.push((c, new_parent, self.last_breakpoint));
             }
         }
 
-        self
+        Ok(self)
     }
 
-    pub fn end(mut self, seq_length: u64) -> TreeSequence {
+    /// Panicking wrapper around [`TreeSequenceBuilder::try_end`], for
+    /// callers that trust their input to already be well-formed.
+    pub fn end(self, seq_length: u64) -> TreeSequence {
+        self.try_end(seq_length).unwrap()
+    }
+
+    /// Flushes every outstanding edge at `seq_length` and returns the
+    /// finished `TreeSequence`, failing if any flushed edge is invalid.
+    pub fn try_end(mut self, seq_length: u64) -> Result<TreeSequence, TreeSequenceError> {
         for (child, parent, left) in self.curr_edges {
-            self.ts.add_edge(child, parent, left, seq_length);
+            self.ts.try_add_edge(child, parent, left, seq_length)?;
         }
 
-        self.ts
+        Ok(self.ts)
     }
 }
 
@@ -73,7 +99,7 @@ macro_rules! treeseq {
 mod test {
     use super::TreeSequenceBuilder;
     use crate::tree::Tree;
-    use crate::treeseq::TreeSequence;
+    use crate::treeseq::{TreeSequence, TreeSequenceError};
 
     fn example_ts() -> TreeSequence {
         TreeSequenceBuilder::new()
@@ -152,4 +178,80 @@ mod test {
 
         assert_eq!(ts_iter.next(), None);
     }
+
+    #[test]
+    fn test_tree_sequence_sample_tracking() {
+        use streaming_iterator::StreamingIterator;
+
+        let mut ts = example_ts();
+        ts.mark_samples(&[0, 1, 2, 3]);
+        let mut ts_iter = ts.streaming_iter();
+
+        let t1 = ts_iter.next().unwrap();
+        assert_eq!(t1.num_samples(4), 2);
+        assert_eq!(t1.num_samples(5), 2);
+        assert_eq!(t1.num_samples(6), 4);
+        assert_eq!(t1.samples_below(4).collect::<Vec<_>>(), vec![0, 1]);
+
+        let t2 = ts_iter.next().unwrap();
+        assert_eq!(t2.num_samples(4), 0);
+        assert_eq!(t2.num_samples(5), 3);
+        assert_eq!(t2.num_samples(6), 4);
+
+        let t3 = ts_iter.next().unwrap();
+        assert_eq!(t3.num_samples(0), 1);
+        assert_eq!(t3.num_samples(5), 3);
+        assert_eq!(t3.num_samples(6), 0);
+
+        assert_eq!(ts_iter.next(), None);
+    }
+
+    #[test]
+    fn test_try_transplant_unknown_child() {
+        let result = TreeSequenceBuilder::new()
+            .insert(vec![0, 1], 4)
+            .try_transplant(vec![2], Some(5));
+        assert_eq!(result.err(), Some(TreeSequenceError::UnknownChild));
+    }
+
+    #[test]
+    fn test_tree_sequence_tree_at() {
+        let ts = example_ts();
+
+        let t0 = ts.tree_at(0);
+        assert_eq!(t0.parent(0), Some(4));
+        assert_eq!(t0.parent(4), Some(6));
+        assert_eq!(t0.parent(6), None);
+
+        let t1 = ts.tree_at(1);
+        assert_eq!(t1.parent(0), Some(6));
+        assert_eq!(t1.parent(1), Some(5));
+        assert_eq!(t1.parent(4), Some(6));
+
+        let t2 = ts.tree_at(2);
+        assert_eq!(t2.parent(0), None);
+        assert_eq!(t2.parent(5), None);
+        assert_eq!(t2.parent(1), Some(5));
+        assert_eq!(t2.parent(4), Some(6));
+    }
+
+    #[test]
+    fn test_tree_sequence_children_and_roots() {
+        use streaming_iterator::StreamingIterator;
+
+        let ts = example_ts();
+        let mut ts_iter = ts.streaming_iter();
+
+        let t1 = ts_iter.next().unwrap();
+        assert_eq!(t1.roots().collect::<Vec<_>>(), vec![6]);
+        let mut children_of_6: Vec<_> = t1.children(6).collect();
+        children_of_6.sort();
+        assert_eq!(children_of_6, vec![4, 5]);
+
+        let t3 = ts_iter.nth(1).unwrap();
+        let mut roots = t3.roots().collect::<Vec<_>>();
+        roots.sort();
+        assert_eq!(roots, vec![0, 5, 6]);
+        assert_eq!(t3.children(6).collect::<Vec<_>>(), vec![4]);
+    }
 }